@@ -0,0 +1,107 @@
+//! In-session `/`-prefixed commands. These let an interactive user change a
+//! session's defaults between turns instead of restarting the process.
+//!
+//! This module only turns a line of text into a [`SessionCommand`]; applying
+//! it to a running session is the caller's job (see `apply_session_command`
+//! in `main.rs`), since that requires mutating session state this module
+//! doesn't own.
+
+use anyhow::{Result, bail};
+use codex_core::protocol::{AskForApproval, SandboxPolicy};
+use codex_protocol::config_types::ReasoningEffort as ReasoningEffortConfig;
+use std::path::PathBuf;
+
+#[derive(Debug, Clone)]
+pub enum SessionCommand {
+    Model(String),
+    Effort(ReasoningEffortConfig),
+    Approval(AskForApproval),
+    Sandbox(SandboxPolicy),
+    Cwd(PathBuf),
+    Schema(PathBuf),
+    Show,
+}
+
+/// Parse a line typed at the `codex>` prompt. Returns `Ok(None)` for
+/// ordinary text (not a command), so the caller can fall through to
+/// `send_turn`.
+pub fn parse(line: &str) -> Result<Option<SessionCommand>> {
+    let line = line.trim();
+    let Some(rest) = line.strip_prefix('/') else {
+        return Ok(None);
+    };
+
+    let mut parts = rest.splitn(2, char::is_whitespace);
+    let name = parts.next().unwrap_or_default();
+    let arg = parts.next().unwrap_or_default().trim();
+
+    let command = match name {
+        "model" => {
+            if arg.is_empty() {
+                bail!("/model requires a model name");
+            }
+            SessionCommand::Model(arg.to_string())
+        }
+        "effort" => SessionCommand::Effort(parse_effort(arg)?),
+        "approval" => SessionCommand::Approval(parse_approval(arg)?),
+        "sandbox" => SessionCommand::Sandbox(parse_sandbox(arg)?),
+        "cwd" => {
+            if arg.is_empty() {
+                bail!("/cwd requires a path");
+            }
+            SessionCommand::Cwd(PathBuf::from(arg))
+        }
+        "schema" => {
+            if arg.is_empty() {
+                bail!("/schema requires a path to a JSON Schema file");
+            }
+            if arg == "-" {
+                bail!(
+                    "/schema - is not supported in-session: stdin is the command input stream here; pass a file path instead"
+                );
+            }
+            SessionCommand::Schema(PathBuf::from(arg))
+        }
+        "show" => SessionCommand::Show,
+        other => bail!(
+            "unknown command /{other} (expected model, effort, approval, sandbox, cwd, schema, show)"
+        ),
+    };
+    Ok(Some(command))
+}
+
+fn parse_effort(value: &str) -> Result<ReasoningEffortConfig> {
+    match value {
+        "minimal" => Ok(ReasoningEffortConfig::Minimal),
+        "low" => Ok(ReasoningEffortConfig::Low),
+        "medium" => Ok(ReasoningEffortConfig::Medium),
+        "high" => Ok(ReasoningEffortConfig::High),
+        other => bail!("/effort expects one of minimal|low|medium|high, got {other:?}"),
+    }
+}
+
+fn parse_approval(value: &str) -> Result<AskForApproval> {
+    match value {
+        "untrusted" => Ok(AskForApproval::UnlessTrusted),
+        "on-failure" => Ok(AskForApproval::OnFailure),
+        "on-request" => Ok(AskForApproval::OnRequest),
+        "never" => Ok(AskForApproval::Never),
+        other => bail!("/approval expects one of untrusted|on-failure|on-request|never, got {other:?}"),
+    }
+}
+
+fn parse_sandbox(value: &str) -> Result<SandboxPolicy> {
+    match value {
+        "read-only" => Ok(SandboxPolicy::ReadOnly),
+        "workspace-write" => Ok(SandboxPolicy::WorkspaceWrite {
+            writable_roots: Vec::new(),
+            network_access: false,
+            exclude_tmpdir_env_var: false,
+            exclude_slash_tmp: false,
+        }),
+        "danger-full-access" => Ok(SandboxPolicy::DangerFullAccess),
+        other => bail!(
+            "/sandbox expects one of read-only|workspace-write|danger-full-access, got {other:?}"
+        ),
+    }
+}
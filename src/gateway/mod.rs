@@ -0,0 +1,118 @@
+//! Control gateway: lets an external program drive a [`crate::CodexSession`]
+//! over a Unix domain socket or a WebSocket instead of stdin.
+//!
+//! The gateway itself only knows how to accept connections, parse inbound
+//! framed JSON requests, and fan outbound JSON lines back out to every
+//! connected client. It has no opinion on session state; the caller wires
+//! [`GatewayHandle::inbound`] and [`GatewayHandle::outbound`] into the turn
+//! loop.
+
+mod socket;
+mod websocket;
+
+use anyhow::{Context, Result, bail};
+use std::path::PathBuf;
+use std::str::FromStr;
+use tokio::sync::broadcast;
+use tokio::sync::mpsc::{self, UnboundedReceiver, UnboundedSender};
+
+/// Parsed form of the `--listen` flag: `unix:/path/to.sock` or `ws://host:port`.
+#[derive(Debug, Clone)]
+pub enum ListenAddr {
+    Unix(PathBuf),
+    WebSocket(String),
+}
+
+impl FromStr for ListenAddr {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        if let Some(path) = s.strip_prefix("unix:") {
+            Ok(ListenAddr::Unix(PathBuf::from(path)))
+        } else if let Some(rest) = s.strip_prefix("ws://") {
+            Ok(ListenAddr::WebSocket(rest.to_string()))
+        } else {
+            bail!("--listen expects `unix:<path>` or `ws://<host>:<port>`, got {s:?}")
+        }
+    }
+}
+
+/// One request sent by a connected client.
+#[derive(Debug, Clone)]
+pub enum ClientRequest {
+    SendTurn { text: String },
+    Interrupt,
+    RespondApproval { id: String, decision: String },
+}
+
+fn parse_client_request(line: &str) -> Result<ClientRequest> {
+    let value: serde_json::Value =
+        serde_json::from_str(line).context("gateway message is not valid JSON")?;
+    let method = value
+        .get("method")
+        .and_then(|m| m.as_str())
+        .context("gateway message missing `method`")?;
+    let params = value.get("params").cloned().unwrap_or(serde_json::Value::Null);
+
+    match method {
+        "sendTurn" => {
+            let text = params
+                .get("text")
+                .and_then(|t| t.as_str())
+                .context("sendTurn requires params.text")?;
+            Ok(ClientRequest::SendTurn {
+                text: text.to_string(),
+            })
+        }
+        "interrupt" => Ok(ClientRequest::Interrupt),
+        "respondApproval" => {
+            let id = params
+                .get("id")
+                .and_then(|v| v.as_str())
+                .context("respondApproval requires params.id")?;
+            let decision = params
+                .get("decision")
+                .and_then(|v| v.as_str())
+                .context("respondApproval requires params.decision")?;
+            Ok(ClientRequest::RespondApproval {
+                id: id.to_string(),
+                decision: decision.to_string(),
+            })
+        }
+        other => bail!("unknown gateway method: {other}"),
+    }
+}
+
+/// A running gateway: inbound client requests flow through `inbound`, and
+/// anything sent on `outbound` is mirrored to every connected client as a
+/// newline-delimited JSON line.
+pub struct GatewayHandle {
+    pub inbound: UnboundedReceiver<ClientRequest>,
+    pub outbound: broadcast::Sender<String>,
+}
+
+impl GatewayHandle {
+    pub fn broadcast_json(&self, value: &serde_json::Value) {
+        // Dropped if no clients are connected yet; that's fine, the gateway
+        // has no replay buffer.
+        let _ = self.outbound.send(value.to_string());
+    }
+}
+
+pub async fn spawn(addr: ListenAddr) -> Result<GatewayHandle> {
+    let (inbound_tx, inbound_rx): (UnboundedSender<ClientRequest>, UnboundedReceiver<ClientRequest>) =
+        mpsc::unbounded_channel();
+    let (outbound_tx, _) = broadcast::channel(1024);
+
+    match addr {
+        ListenAddr::Unix(path) => socket::spawn_unix(path, inbound_tx, outbound_tx.clone()).await?,
+        ListenAddr::WebSocket(bind_addr) => {
+            websocket::spawn_websocket(bind_addr, inbound_tx, outbound_tx.clone()).await?
+        }
+    }
+
+    Ok(GatewayHandle {
+        inbound: inbound_rx,
+        outbound: outbound_tx,
+    })
+}
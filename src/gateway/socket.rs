@@ -0,0 +1,89 @@
+use std::path::PathBuf;
+
+use anyhow::{Context, Result};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{UnixListener, UnixStream};
+use tokio::sync::broadcast;
+use tokio::sync::mpsc::UnboundedSender;
+
+use super::{ClientRequest, parse_client_request};
+
+pub async fn spawn_unix(
+    path: PathBuf,
+    inbound: UnboundedSender<ClientRequest>,
+    outbound: broadcast::Sender<String>,
+) -> Result<()> {
+    if path.exists() {
+        std::fs::remove_file(&path)
+            .with_context(|| format!("failed to remove stale socket at {}", path.display()))?;
+    }
+    let listener = UnixListener::bind(&path)
+        .with_context(|| format!("failed to bind unix socket at {}", path.display()))?;
+
+    tokio::spawn(async move {
+        loop {
+            match listener.accept().await {
+                Ok((stream, _addr)) => {
+                    let inbound = inbound.clone();
+                    let outbound_rx = outbound.subscribe();
+                    tokio::spawn(handle_connection(stream, inbound, outbound_rx));
+                }
+                Err(err) => {
+                    eprintln!("gateway: unix accept failed: {err}");
+                    break;
+                }
+            }
+        }
+    });
+
+    Ok(())
+}
+
+async fn handle_connection(
+    stream: UnixStream,
+    inbound: UnboundedSender<ClientRequest>,
+    mut outbound_rx: broadcast::Receiver<String>,
+) {
+    let (read_half, mut write_half) = stream.into_split();
+    let mut lines = BufReader::new(read_half).lines();
+
+    loop {
+        tokio::select! {
+            line = lines.next_line() => {
+                match line {
+                    Ok(Some(line)) => {
+                        if line.trim().is_empty() {
+                            continue;
+                        }
+                        match parse_client_request(&line) {
+                            Ok(request) => {
+                                if inbound.send(request).is_err() {
+                                    break;
+                                }
+                            }
+                            Err(err) => eprintln!("gateway: invalid client message: {err}"),
+                        }
+                    }
+                    Ok(None) => break,
+                    Err(err) => {
+                        eprintln!("gateway: unix read error: {err}");
+                        break;
+                    }
+                }
+            }
+            message = outbound_rx.recv() => {
+                match message {
+                    Ok(line) => {
+                        if write_half.write_all(line.as_bytes()).await.is_err()
+                            || write_half.write_all(b"\n").await.is_err()
+                        {
+                            break;
+                        }
+                    }
+                    Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                    Err(broadcast::error::RecvError::Closed) => break,
+                }
+            }
+        }
+    }
+}
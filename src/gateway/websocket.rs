@@ -0,0 +1,90 @@
+use anyhow::{Context, Result};
+use futures_util::{SinkExt, StreamExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::broadcast;
+use tokio::sync::mpsc::UnboundedSender;
+use tokio_tungstenite::tungstenite::Message;
+
+use super::{ClientRequest, parse_client_request};
+
+pub async fn spawn_websocket(
+    bind_addr: String,
+    inbound: UnboundedSender<ClientRequest>,
+    outbound: broadcast::Sender<String>,
+) -> Result<()> {
+    let listener = TcpListener::bind(&bind_addr)
+        .await
+        .with_context(|| format!("failed to bind websocket listener on {bind_addr}"))?;
+
+    tokio::spawn(async move {
+        loop {
+            match listener.accept().await {
+                Ok((stream, _addr)) => {
+                    let inbound = inbound.clone();
+                    let outbound_rx = outbound.subscribe();
+                    tokio::spawn(handle_connection(stream, inbound, outbound_rx));
+                }
+                Err(err) => {
+                    eprintln!("gateway: websocket accept failed: {err}");
+                    break;
+                }
+            }
+        }
+    });
+
+    Ok(())
+}
+
+async fn handle_connection(
+    stream: TcpStream,
+    inbound: UnboundedSender<ClientRequest>,
+    mut outbound_rx: broadcast::Receiver<String>,
+) {
+    let ws_stream = match tokio_tungstenite::accept_async(stream).await {
+        Ok(ws_stream) => ws_stream,
+        Err(err) => {
+            eprintln!("gateway: websocket handshake failed: {err}");
+            return;
+        }
+    };
+    let (mut write_half, mut read_half) = ws_stream.split();
+
+    loop {
+        tokio::select! {
+            message = read_half.next() => {
+                match message {
+                    Some(Ok(Message::Text(text))) => {
+                        if text.trim().is_empty() {
+                            continue;
+                        }
+                        match parse_client_request(&text) {
+                            Ok(request) => {
+                                if inbound.send(request).is_err() {
+                                    break;
+                                }
+                            }
+                            Err(err) => eprintln!("gateway: invalid client message: {err}"),
+                        }
+                    }
+                    Some(Ok(Message::Close(_))) | None => break,
+                    Some(Ok(_)) => continue,
+                    Some(Err(err)) => {
+                        eprintln!("gateway: websocket read error: {err}");
+                        break;
+                    }
+                }
+            }
+            line = outbound_rx.recv() => {
+                match line {
+                    Ok(line) => {
+                        if write_half.send(Message::Text(line)).await.is_err() {
+                            break;
+                        }
+                    }
+                    Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                    Err(broadcast::error::RecvError::Closed) => break,
+                }
+            }
+        }
+    }
+}
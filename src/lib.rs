@@ -0,0 +1,1037 @@
+//! Library side of `cleon`: everything needed to drive a Codex session
+//! programmatically — `CodexSession`, the `CodexSessionBuilder`, `TurnResult`
+//! and the approval types — so embedders (tests, other tools, a GUI) can run
+//! Codex turns without shelling out to the CLI binary. `main.rs` is a thin
+//! wrapper around this crate: login/logout/status, argument parsing and the
+//! in-session `/` commands live there, everything else lives here.
+
+use std::collections::VecDeque;
+use std::io::Read;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+use anyhow::{Context, Result, bail};
+use codex_core::auth::enforce_login_restrictions;
+use codex_core::config::{Config, ConfigOverrides};
+use codex_core::find_conversation_path_by_id_str;
+use codex_core::protocol::{
+    AskForApproval, Event, EventMsg, Op, ReviewDecision, SandboxPolicy, SessionSource,
+};
+use codex_core::{AuthManager, ConversationManager, NewConversation};
+use codex_exec::event_processor_with_jsonl_output::EventProcessorWithJsonOutput;
+use codex_exec::exec_events::{ThreadEvent, ThreadItemDetails, Usage};
+use codex_protocol::config_types::{ReasoningEffort as ReasoningEffortConfig, ReasoningSummary};
+use codex_protocol::user_input::UserInput;
+use futures_core::Stream;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tokio::io::{AsyncBufReadExt, BufReader};
+use tokio::signal;
+use tokio::sync::mpsc::{UnboundedReceiver, UnboundedSender, unbounded_channel};
+
+pub mod gateway;
+pub mod webhook;
+
+pub use gateway::{ClientRequest, GatewayHandle, ListenAddr};
+pub use webhook::WebhookSink;
+
+/// A running Codex session: one live conversation plus the defaults
+/// (`model`, `effort`, `cwd`, approval/sandbox policy) applied to every turn
+/// submitted through it. Build one with [`CodexSession::new`] for Codex's
+/// on-disk config defaults, or [`CodexSession::builder`] to override them.
+pub struct CodexSession {
+    conversation: Arc<codex_core::CodexConversation>,
+    event_rx: UnboundedReceiver<Event>,
+    event_processor: EventProcessorWithJsonOutput,
+    bootstrap_events: Vec<ThreadEvent>,
+    session_id: Option<String>,
+    rollout_path: Option<String>,
+    default_cwd: PathBuf,
+    default_approval: AskForApproval,
+    default_sandbox_policy: SandboxPolicy,
+    default_model: String,
+    default_effort: Option<ReasoningEffortConfig>,
+    default_summary: ReasoningSummary,
+    gateway: Option<GatewayHandle>,
+    queued_turns: VecDeque<String>,
+    webhook: Option<Arc<WebhookSink>>,
+    approval_timeout: Option<std::time::Duration>,
+    approval_timeout_decision: ReviewDecision,
+    output_schema: Option<OutputSchema>,
+    pending_approvals: Arc<Mutex<PendingApprovals>>,
+}
+
+/// A JSON Schema document supplied via `--output-schema`/`/schema` (or the
+/// builder), kept alongside the source it was loaded from so validation
+/// failures can name it instead of just dumping the schema body.
+#[derive(Debug, Clone)]
+pub struct OutputSchema {
+    source: String,
+    document: serde_json::Value,
+}
+
+impl OutputSchema {
+    /// Build one from an in-memory JSON Schema document, validating that it
+    /// actually compiles before accepting it. `source` is a human-readable
+    /// label (a file path, `-` for stdin, or anything else) used in error
+    /// messages.
+    pub fn from_value(source: impl Into<String>, document: serde_json::Value) -> Result<Self> {
+        let source = source.into();
+        jsonschema::JSONSchema::compile(&document).map_err(|err| {
+            anyhow::anyhow!("--output-schema {source:?} is not a valid JSON Schema: {err}")
+        })?;
+        Ok(Self { source, document })
+    }
+}
+
+/// Load a `--output-schema`/`/schema` argument, which is either a file path
+/// or `-` to read the schema document from stdin.
+pub fn load_output_schema(source: &str) -> Result<OutputSchema> {
+    let raw = if source.trim() == "-" {
+        let mut buffer = String::new();
+        std::io::stdin()
+            .read_to_string(&mut buffer)
+            .context("failed to read --output-schema from stdin")?;
+        buffer
+    } else {
+        std::fs::read_to_string(source)
+            .with_context(|| format!("failed to read --output-schema file {source:?}"))?
+    };
+    let document: serde_json::Value = serde_json::from_str(&raw)
+        .with_context(|| format!("--output-schema {source:?} is not valid JSON"))?;
+    OutputSchema::from_value(source, document)
+}
+
+/// Build a [`CodexSession`] with overrides layered on top of Codex's on-disk
+/// config, the way `kanidm_client`'s `ClientBuilder` layers overrides on top
+/// of its config file. Anything left unset falls back to what
+/// [`CodexSession::new`] would have picked anyway.
+#[derive(Debug, Default)]
+pub struct CodexSessionBuilder {
+    resume_session: Option<String>,
+    model: Option<String>,
+    effort: Option<ReasoningEffortConfig>,
+    cwd: Option<PathBuf>,
+    approval: Option<AskForApproval>,
+    sandbox_policy: Option<SandboxPolicy>,
+    approval_timeout: Option<std::time::Duration>,
+    approval_timeout_decision: Option<ReviewDecision>,
+    output_schema: Option<OutputSchema>,
+}
+
+impl CodexSessionBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Resume a previous session by its session UUID instead of starting a
+    /// fresh conversation.
+    pub fn resume(mut self, session_id: impl Into<String>) -> Self {
+        self.resume_session = Some(session_id.into());
+        self
+    }
+
+    pub fn model(mut self, model: impl Into<String>) -> Self {
+        self.model = Some(model.into());
+        self
+    }
+
+    pub fn effort(mut self, effort: ReasoningEffortConfig) -> Self {
+        self.effort = Some(effort);
+        self
+    }
+
+    pub fn cwd(mut self, cwd: impl Into<PathBuf>) -> Self {
+        self.cwd = Some(cwd.into());
+        self
+    }
+
+    pub fn approval(mut self, approval: AskForApproval) -> Self {
+        self.approval = Some(approval);
+        self
+    }
+
+    pub fn sandbox(mut self, policy: SandboxPolicy) -> Self {
+        self.sandbox_policy = Some(policy);
+        self
+    }
+
+    /// Auto-resolve an outstanding exec/patch approval after `timeout`
+    /// instead of waiting forever; see [`Self::approval_timeout_decision`]
+    /// for what gets submitted when it elapses.
+    pub fn approval_timeout(mut self, timeout: std::time::Duration) -> Self {
+        self.approval_timeout = Some(timeout);
+        self
+    }
+
+    pub fn approval_timeout_decision(mut self, decision: ReviewDecision) -> Self {
+        self.approval_timeout_decision = Some(decision);
+        self
+    }
+
+    /// Require every turn's `final_message` to validate against this JSON
+    /// Schema; see [`load_output_schema`] to build one from a file or stdin.
+    pub fn output_schema(mut self, schema: OutputSchema) -> Self {
+        self.output_schema = Some(schema);
+        self
+    }
+
+    pub async fn build(self) -> Result<CodexSession> {
+        let mut session = CodexSession::new(self.resume_session).await?;
+        if let Some(model) = self.model {
+            session.default_model = model;
+        }
+        if let Some(effort) = self.effort {
+            session.default_effort = Some(effort);
+        }
+        if let Some(cwd) = self.cwd {
+            session.default_cwd = cwd;
+        }
+        if let Some(approval) = self.approval {
+            session.default_approval = approval;
+        }
+        if let Some(policy) = self.sandbox_policy {
+            session.default_sandbox_policy = policy;
+        }
+        session.approval_timeout = self.approval_timeout;
+        if let Some(decision) = self.approval_timeout_decision {
+            session.approval_timeout_decision = decision;
+        }
+        session.output_schema = self.output_schema;
+        Ok(session)
+    }
+}
+
+/// Which kind of approval request this is, i.e. which `Op` variant answers
+/// it. Also reported on [`ApprovalOutcome`] so JSON consumers don't have to
+/// infer it from context.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ApprovalKind {
+    Exec,
+    Patch,
+}
+
+/// How often to scan pending approvals for ones that have exceeded
+/// `--approval-timeout`. Independent of the timeout itself so a short
+/// timeout still gets checked promptly.
+const APPROVAL_TIMEOUT_POLL_INTERVAL: std::time::Duration = std::time::Duration::from_millis(250);
+
+/// An independently-ownable way to answer approvals raised by an in-flight
+/// turn, obtained via [`CodexSession::approval_handle`]. Unlike
+/// [`CodexSession::respond_approval`] it holds its own `Arc`s instead of
+/// borrowing the session, so it can be called while something else (e.g.
+/// [`CodexSession::stream_turn`]'s stream) holds `&mut CodexSession`.
+#[derive(Clone)]
+pub struct ApprovalHandle {
+    conversation: Arc<codex_core::CodexConversation>,
+    pending: Arc<Mutex<PendingApprovals>>,
+}
+
+impl ApprovalHandle {
+    /// Resolve a single outstanding approval by id. Errors if no approval
+    /// with that id is currently outstanding (already answered, expired, or
+    /// never requested).
+    pub async fn respond(&self, id: String, decision: ReviewDecision) -> Result<()> {
+        let kind = {
+            let mut pending = self.pending.lock().expect("pending approvals mutex poisoned");
+            pending.take_id(&id)
+        };
+        let Some(kind) = kind else {
+            bail!("no pending approval with id {id:?}");
+        };
+        match kind {
+            ApprovalKind::Exec => {
+                self.conversation
+                    .submit(Op::ExecApproval { id, decision })
+                    .await?;
+            }
+            ApprovalKind::Patch => {
+                self.conversation
+                    .submit(Op::PatchApproval { id, decision })
+                    .await?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Approvals currently outstanding for the active turn, addressable either
+/// in arrival order (stdin, which can only answer the oldest one) or by
+/// `id` (a gateway client or [`CodexSession::respond_approval`], which may
+/// answer them out of order).
+#[derive(Default)]
+struct PendingApprovals {
+    order: VecDeque<String>,
+    entries: std::collections::HashMap<String, PendingApprovalEntry>,
+}
+
+struct PendingApprovalEntry {
+    kind: ApprovalKind,
+    requested_at: std::time::Instant,
+}
+
+impl PendingApprovals {
+    fn push(&mut self, id: String, kind: ApprovalKind) {
+        self.entries.insert(
+            id.clone(),
+            PendingApprovalEntry {
+                kind,
+                requested_at: std::time::Instant::now(),
+            },
+        );
+        self.order.push_back(id);
+    }
+
+    fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Pop the oldest still-outstanding approval (used by the stdin path).
+    fn take_front(&mut self) -> Option<(String, ApprovalKind)> {
+        while let Some(id) = self.order.pop_front() {
+            if let Some(entry) = self.entries.remove(&id) {
+                return Some((id, entry.kind));
+            }
+        }
+        None
+    }
+
+    /// Pop a specific approval by id (used by the gateway path and
+    /// `respond_approval`).
+    fn take_id(&mut self, id: &str) -> Option<ApprovalKind> {
+        let entry = self.entries.remove(id)?;
+        self.order.retain(|pending_id| pending_id != id);
+        Some(entry.kind)
+    }
+
+    /// Pop every approval that has been outstanding longer than `timeout`.
+    fn take_expired(&mut self, timeout: std::time::Duration) -> Vec<(String, ApprovalKind)> {
+        let expired_ids: Vec<String> = self
+            .entries
+            .iter()
+            .filter(|(_, entry)| entry.requested_at.elapsed() >= timeout)
+            .map(|(id, _)| id.clone())
+            .collect();
+        expired_ids
+            .into_iter()
+            .filter_map(|id| self.take_id(&id).map(|kind| (id, kind)))
+            .collect()
+    }
+
+    /// Drain everything still outstanding, e.g. because the turn was
+    /// interrupted and nothing will ever answer them.
+    fn take_all(&mut self) -> Vec<(String, ApprovalKind)> {
+        self.order.clear();
+        self.entries
+            .drain()
+            .map(|(id, entry)| (id, entry.kind))
+            .collect()
+    }
+}
+
+impl CodexSession {
+    /// Start (or resume) a session using Codex's on-disk config as-is. Use
+    /// [`CodexSession::builder`] to override model/effort/cwd/approval/sandbox
+    /// instead of only reading them from config.
+    pub async fn new(resume_session: Option<String>) -> Result<Self> {
+        let config = Arc::new(load_config().await?);
+
+        enforce_login_restrictions(&config)
+            .await
+            .context("login restrictions check failed")?;
+
+        let auth_manager = AuthManager::shared(
+            config.codex_home.clone(),
+            true,
+            config.cli_auth_credentials_store_mode,
+        );
+
+        let conversation_manager =
+            ConversationManager::new(auth_manager.clone(), SessionSource::Cli);
+        let NewConversation {
+            conversation_id: _,
+            conversation,
+            session_configured,
+        } = if let Some(resume) = resume_session {
+            let path = find_conversation_path_by_id_str(&config.codex_home, &resume)
+                .await
+                .context("failed to search for session to resume")?;
+            let Some(rollout_path) = path else {
+                bail!("No saved session found with ID {resume}");
+            };
+            conversation_manager
+                .resume_conversation_from_rollout(
+                    (*config).clone(),
+                    rollout_path,
+                    auth_manager.clone(),
+                )
+                .await?
+        } else {
+            conversation_manager
+                .new_conversation((*config).clone())
+                .await?
+        };
+
+        let (tx, rx) = unbounded_channel::<Event>();
+        let event_conversation = conversation.clone();
+        tokio::spawn(async move {
+            loop {
+                match event_conversation.next_event().await {
+                    Ok(event) => {
+                        if tx.send(event).is_err() {
+                            break;
+                        }
+                    }
+                    Err(err) => {
+                        eprintln!("event stream closed: {err}");
+                        break;
+                    }
+                }
+            }
+        });
+
+        let mut event_processor = EventProcessorWithJsonOutput::new(None);
+        let bootstrap_event = Event {
+            id: String::new(),
+            msg: EventMsg::SessionConfigured(session_configured.clone()),
+        };
+        let bootstrap_events = event_processor.collect_thread_events(&bootstrap_event);
+        let mut session_id = None;
+        let mut rollout_path = None;
+        if let EventMsg::SessionConfigured(cfg) = &bootstrap_event.msg {
+            session_id = Some(cfg.session_id.to_string());
+            rollout_path = Some(cfg.rollout_path.display().to_string());
+        }
+
+        Ok(Self {
+            conversation,
+            event_rx: rx,
+            event_processor,
+            bootstrap_events,
+            session_id,
+            rollout_path,
+            default_cwd: config.cwd.clone(),
+            default_approval: config.approval_policy,
+            default_sandbox_policy: config.sandbox_policy.clone(),
+            default_model: config.model.clone(),
+            default_effort: config.model_reasoning_effort,
+            default_summary: config.model_reasoning_summary,
+            gateway: None,
+            queued_turns: VecDeque::new(),
+            webhook: None,
+            approval_timeout: None,
+            approval_timeout_decision: ReviewDecision::Denied,
+            output_schema: None,
+            pending_approvals: Arc::new(Mutex::new(PendingApprovals::default())),
+        })
+    }
+
+    /// Start building a session with overrides; see [`CodexSessionBuilder`].
+    pub fn builder() -> CodexSessionBuilder {
+        CodexSessionBuilder::new()
+    }
+
+    pub fn attach_gateway(&mut self, gateway: GatewayHandle) {
+        self.gateway = Some(gateway);
+    }
+
+    pub fn attach_webhook(&mut self, webhook: Arc<WebhookSink>) {
+        self.webhook = Some(webhook);
+    }
+
+    async fn forward_webhook(&self, event_type: &str, body: serde_json::Value) {
+        if let Some(webhook) = &self.webhook {
+            webhook.send(event_type, body).await;
+        }
+    }
+
+    /// Mirror an envelope to every connected gateway client, in addition to
+    /// whatever else the caller already did with it (stdout, the webhook
+    /// sink). A no-op if no gateway is attached.
+    fn forward_gateway(&self, body: &serde_json::Value) {
+        if let Some(gateway) = &self.gateway {
+            gateway.broadcast_json(body);
+        }
+    }
+
+    fn pending_approvals(&self) -> std::sync::MutexGuard<'_, PendingApprovals> {
+        self.pending_approvals
+            .lock()
+            .expect("pending approvals mutex poisoned")
+    }
+
+    pub fn session_id(&self) -> Option<&str> {
+        self.session_id.as_deref()
+    }
+
+    pub fn gateway(&self) -> Option<&GatewayHandle> {
+        self.gateway.as_ref()
+    }
+
+    pub fn settings_json(&self) -> serde_json::Value {
+        json!({
+            "model": self.default_model,
+            "effort": self.default_effort,
+            "approval": self.default_approval,
+            "sandbox_policy": self.default_sandbox_policy,
+            "cwd": self.default_cwd,
+        })
+    }
+
+    pub fn set_model(&mut self, model: impl Into<String>) {
+        self.default_model = model.into();
+    }
+
+    pub fn set_effort(&mut self, effort: ReasoningEffortConfig) {
+        self.default_effort = Some(effort);
+    }
+
+    pub fn set_approval(&mut self, approval: AskForApproval) {
+        self.default_approval = approval;
+    }
+
+    pub fn set_sandbox_policy(&mut self, policy: SandboxPolicy) {
+        self.default_sandbox_policy = policy;
+    }
+
+    pub fn set_cwd(&mut self, cwd: PathBuf) {
+        self.default_cwd = cwd;
+    }
+
+    pub fn set_output_schema(&mut self, schema: Option<OutputSchema>) {
+        self.output_schema = schema;
+    }
+
+    pub fn has_output_schema(&self) -> bool {
+        self.output_schema.is_some()
+    }
+
+    /// Block until a gateway client sends `sendTurn`, draining any turn that
+    /// arrived (and was queued) while a previous turn was still running.
+    /// Returns `None` once the gateway is gone (no more clients can ever
+    /// connect again).
+    pub async fn next_gateway_turn(&mut self) -> Option<String> {
+        if let Some(text) = self.queued_turns.pop_front() {
+            return Some(text);
+        }
+        loop {
+            let gateway = self.gateway.as_mut()?;
+            match gateway.inbound.recv().await {
+                Some(ClientRequest::SendTurn { text }) => return Some(text),
+                Some(ClientRequest::Interrupt) | Some(ClientRequest::RespondApproval { .. }) => {
+                    // Nothing is running between turns; ignore.
+                    continue;
+                }
+                None => return None,
+            }
+        }
+    }
+
+    async fn submit_user_turn(&mut self, user_text: String) -> Result<()> {
+        let items = vec![UserInput::Text { text: user_text }];
+
+        self.conversation
+            .submit(Op::UserTurn {
+                items,
+                cwd: self.default_cwd.clone(),
+                approval_policy: self.default_approval,
+                sandbox_policy: self.default_sandbox_policy.clone(),
+                model: self.default_model.clone(),
+                effort: self.default_effort,
+                summary: self.default_summary,
+                final_output_json_schema: self.output_schema.as_ref().map(|s| s.document.clone()),
+            })
+            .await?;
+        Ok(())
+    }
+
+    pub async fn send_turn(&mut self, user_text: String, emit_json_events: bool) -> Result<TurnResult> {
+        self.submit_user_turn(user_text).await?;
+        self.collect_turn_events(emit_json_events).await
+    }
+
+    /// Stream every `ThreadEvent` produced by one turn as it arrives instead
+    /// of waiting for the aggregated `TurnResult`. Approval requests are not
+    /// `ThreadEvent`s and do not appear in this stream; they are announced
+    /// only as `approval.request` envelopes on stdout and to any attached
+    /// gateway or webhook. Since polling this stream holds `&mut self` for as
+    /// long as it runs, grab an [`ApprovalHandle`] with
+    /// [`Self::approval_handle`] *before* calling `stream_turn` and answer
+    /// approvals through the handle while the stream is being polled.
+    pub fn stream_turn(&mut self, user_text: String) -> impl Stream<Item = ThreadEvent> + '_ {
+        async_stream::stream! {
+            if let Err(err) = self.submit_user_turn(user_text).await {
+                eprintln!("stream_turn: failed to submit turn: {err:?}");
+                return;
+            }
+
+            let (tx, mut rx) = unbounded_channel();
+            let mut collecting = Box::pin(self.collect_turn_events_inner(false, Some(tx)));
+            loop {
+                tokio::select! {
+                    biased;
+                    maybe_event = rx.recv() => {
+                        if let Some(event) = maybe_event {
+                            yield event;
+                        }
+                    }
+                    outcome = &mut collecting => {
+                        while let Ok(event) = rx.try_recv() {
+                            yield event;
+                        }
+                        if let Err(err) = outcome {
+                            eprintln!("stream_turn: turn ended with error: {err:?}");
+                        }
+                        break;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Get a cheaply-cloneable [`ApprovalHandle`] that can answer approvals
+    /// concurrently with an in-flight [`Self::stream_turn`], since it holds
+    /// its own `Arc`s rather than borrowing `self`.
+    pub fn approval_handle(&self) -> ApprovalHandle {
+        ApprovalHandle {
+            conversation: self.conversation.clone(),
+            pending: self.pending_approvals.clone(),
+        }
+    }
+
+    /// Resolve a single outstanding approval by id from outside an in-flight
+    /// turn, e.g. one surfaced by [`Self::stream_turn`]. Equivalent to
+    /// `session.approval_handle().respond(id, decision)`; prefer
+    /// [`Self::approval_handle`] directly when answering approvals while
+    /// `stream_turn`'s stream is still being polled, since that already
+    /// holds `&mut self`.
+    pub async fn respond_approval(&self, id: String, decision: ReviewDecision) -> Result<()> {
+        self.approval_handle().respond(id, decision).await
+    }
+
+    /// If `--output-schema`/`/schema` is set, parse `final_message` as JSON
+    /// and validate it against the schema, recording either the validated
+    /// object or a structured error — never both.
+    fn validate_output_schema(&self, result: &mut TurnResult) {
+        let Some(schema) = &self.output_schema else {
+            return;
+        };
+        let Some(text) = &result.final_message else {
+            return;
+        };
+
+        let instance = match serde_json::from_str::<serde_json::Value>(text) {
+            Ok(instance) => instance,
+            Err(err) => {
+                result.errors.push(format!(
+                    "final_message is not valid JSON per --output-schema {}: {err}",
+                    schema.source
+                ));
+                result.schema_validation_failed = true;
+                return;
+            }
+        };
+
+        let compiled = match jsonschema::JSONSchema::compile(&schema.document) {
+            Ok(compiled) => compiled,
+            Err(err) => {
+                result
+                    .errors
+                    .push(format!("invalid --output-schema {}: {err}", schema.source));
+                result.schema_validation_failed = true;
+                return;
+            }
+        };
+
+        match compiled.validate(&instance) {
+            Ok(()) => result.structured_output = Some(instance),
+            Err(errors) => {
+                let messages: Vec<String> = errors.map(|e| e.to_string()).collect();
+                result.errors.push(format!(
+                    "final_message failed --output-schema {}: {}",
+                    schema.source,
+                    messages.join("; ")
+                ));
+                result.schema_validation_failed = true;
+            }
+        }
+    }
+
+    async fn collect_turn_events(&mut self, emit_json_events: bool) -> Result<TurnResult> {
+        self.collect_turn_events_inner(emit_json_events, None).await
+    }
+
+    /// Drive one turn to completion, optionally forwarding every
+    /// `ThreadEvent` to `sink` as it arrives (used by [`Self::stream_turn`])
+    /// in addition to building the aggregated [`TurnResult`] every caller
+    /// gets back.
+    async fn collect_turn_events_inner(
+        &mut self,
+        emit_json_events: bool,
+        sink: Option<UnboundedSender<ThreadEvent>>,
+    ) -> Result<TurnResult> {
+        let mut result = TurnResult::default();
+        let mut have_gateway = self.gateway.is_some();
+        let mut stdin_lines = BufReader::new(tokio::io::stdin()).lines();
+
+        if !self.bootstrap_events.is_empty() {
+            for event in &self.bootstrap_events {
+                if emit_json_events {
+                    println!("{}", serde_json::to_string(event)?);
+                }
+                let body = serde_json::to_value(event)?;
+                self.forward_webhook("thread_event", body.clone()).await;
+                self.forward_gateway(&body);
+                if let Some(tx) = &sink {
+                    let _ = tx.send(event.clone());
+                }
+            }
+            result.append_events(std::mem::take(&mut self.bootstrap_events));
+        }
+
+        loop {
+            tokio::select! {
+                _ = signal::ctrl_c() => {
+                    let _ = self.conversation.submit(Op::Interrupt).await;
+                    result.errors.push("Interrupted by user".to_string());
+                    result.record_interrupted_approvals(self.pending_approvals().take_all());
+                    result.completed = true;
+                    break;
+                }
+                maybe_event = self.event_rx.recv() => {
+                    let Some(event) = maybe_event else {
+                        break;
+                    };
+                    match &event.msg {
+                EventMsg::SessionConfigured(cfg) => {
+                    self.session_id = Some(cfg.session_id.to_string());
+                    self.rollout_path = Some(cfg.rollout_path.display().to_string());
+                }
+                EventMsg::ExecApprovalRequest(req) => {
+                    self.pending_approvals().push(event.id.clone(), ApprovalKind::Exec);
+                    let envelope = serde_json::json!({
+                        "type": "approval.request",
+                        "id": event.id,
+                        "kind": "exec",
+                        "command": req.command,
+                        "cwd": req.cwd,
+                        "reason": req.reason,
+                        "risk": req.risk,
+                    });
+                    println!("{envelope}");
+                    self.forward_webhook("approval.request", envelope.clone()).await;
+                    self.forward_gateway(&envelope);
+                    eprintln!(
+                        "APPROVAL REQUEST {}: command={:?} cwd={} reason={:?} risk={:?}",
+                        event.id, req.command, req.cwd.display(), req.reason, req.risk.as_ref().map(|r| r.risk_level.as_str())
+                    );
+                    eprintln!("Respond with: approve | approve_session | deny | abort");
+                }
+                EventMsg::ApplyPatchApprovalRequest(req) => {
+                    self.pending_approvals().push(event.id.clone(), ApprovalKind::Patch);
+                    let envelope = serde_json::json!({
+                        "type": "approval.request",
+                        "id": event.id,
+                        "kind": "patch",
+                        "reason": req.reason,
+                        "grant_root": req.grant_root,
+                        "files": req.changes.keys().collect::<Vec<_>>(),
+                    });
+                    println!("{envelope}");
+                    self.forward_webhook("approval.request", envelope.clone()).await;
+                    self.forward_gateway(&envelope);
+                    eprintln!(
+                        "PATCH APPROVAL {}: files={} reason={:?} grant_root={:?}",
+                        event.id, req.changes.len(), req.reason, req.grant_root
+                    );
+                    eprintln!("Respond with: approve | approve_session | deny | abort");
+                        }
+                        _ => {}
+                    }
+                    let thread_events = self.event_processor.collect_thread_events(&event);
+                    for ev in &thread_events {
+                        if emit_json_events {
+                            println!("{}", serde_json::to_string(ev)?);
+                        }
+                        let body = serde_json::to_value(ev)?;
+                        self.forward_webhook("thread_event", body.clone()).await;
+                        self.forward_gateway(&body);
+                        if let Some(tx) = &sink {
+                            let _ = tx.send(ev.clone());
+                        }
+                    }
+                    result.append_events(thread_events);
+                    if result.turn_complete() {
+                        break;
+                    }
+                }
+                line = stdin_lines.next_line(), if !self.pending_approvals().is_empty() && !have_gateway => {
+                    let Some(line) = line? else { continue };
+                    let taken = self.pending_approvals().take_front();
+                    if let Some((id, kind)) = taken {
+                        match parse_decision(&line) {
+                            Some(decision) => {
+                                match kind {
+                                    ApprovalKind::Exec => {
+                                        self.conversation.submit(Op::ExecApproval { id: id.clone(), decision }).await?;
+                                    }
+                                    ApprovalKind::Patch => {
+                                        self.conversation.submit(Op::PatchApproval { id: id.clone(), decision }).await?;
+                                    }
+                                }
+                                result.approvals.push(ApprovalOutcome {
+                                    id,
+                                    kind,
+                                    decision,
+                                    resolution: ApprovalResolution::User,
+                                });
+                            }
+                            None => {
+                                eprintln!("invalid approval response, expected one of: approve, approve_session, deny, abort");
+                                self.pending_approvals().push(id, kind);
+                            }
+                        }
+                    }
+                }
+                _ = tokio::time::sleep(APPROVAL_TIMEOUT_POLL_INTERVAL),
+                    if self.approval_timeout.is_some() && !self.pending_approvals().is_empty() =>
+                {
+                    let timeout = self.approval_timeout.expect("guarded by is_some() above");
+                    let expired = self.pending_approvals().take_expired(timeout);
+                    for (id, kind) in expired {
+                        let decision = self.approval_timeout_decision;
+                        match kind {
+                            ApprovalKind::Exec => {
+                                self.conversation.submit(Op::ExecApproval { id: id.clone(), decision }).await?;
+                            }
+                            ApprovalKind::Patch => {
+                                self.conversation.submit(Op::PatchApproval { id: id.clone(), decision }).await?;
+                            }
+                        }
+                        eprintln!("approval {id} timed out after {timeout:?}, auto-submitting {decision:?}");
+                        result.approvals.push(ApprovalOutcome {
+                            id,
+                            kind,
+                            decision,
+                            resolution: ApprovalResolution::Timeout,
+                        });
+                    }
+                }
+                gateway_msg = recv_gateway(self.gateway.as_mut()), if have_gateway => {
+                    match gateway_msg {
+                        Some(ClientRequest::SendTurn { text }) => {
+                            // A turn is already running; queue it for
+                            // `next_gateway_turn` once this one completes.
+                            self.queued_turns.push_back(text);
+                        }
+                        Some(ClientRequest::Interrupt) => {
+                            let _ = self.conversation.submit(Op::Interrupt).await;
+                            result.errors.push("Interrupted by gateway client".to_string());
+                            result.record_interrupted_approvals(self.pending_approvals().take_all());
+                            result.completed = true;
+                            break;
+                        }
+                        Some(ClientRequest::RespondApproval { id, decision }) => {
+                            let taken = self.pending_approvals().take_id(&id);
+                            match taken {
+                                Some(kind) => match parse_decision(&decision) {
+                                    Some(decision) => {
+                                        match kind {
+                                            ApprovalKind::Exec => {
+                                                self.conversation.submit(Op::ExecApproval { id: id.clone(), decision }).await?;
+                                            }
+                                            ApprovalKind::Patch => {
+                                                self.conversation.submit(Op::PatchApproval { id: id.clone(), decision }).await?;
+                                            }
+                                        }
+                                        result.approvals.push(ApprovalOutcome {
+                                            id,
+                                            kind,
+                                            decision,
+                                            resolution: ApprovalResolution::User,
+                                        });
+                                    }
+                                    None => {
+                                        eprintln!("gateway: invalid approval decision {decision:?} for {id}");
+                                        self.pending_approvals().push(id, kind);
+                                    }
+                                },
+                                None => eprintln!("gateway: respondApproval for unknown or already-resolved id {id}"),
+                            }
+                        }
+                        None => {
+                            // Gateway inbound channel closed for good; fall
+                            // back to stdin-driven approvals.
+                            have_gateway = false;
+                        }
+                    }
+                }
+            }
+        }
+
+        self.validate_output_schema(&mut result);
+        let turn_result_envelope = serde_json::json!({ "type": "turn.result", "result": &result });
+        self.forward_webhook("turn.result", turn_result_envelope.clone()).await;
+        self.forward_gateway(&turn_result_envelope);
+
+        Ok(result)
+    }
+
+    pub async fn shutdown(&self) -> Result<()> {
+        let _ = self.conversation.submit(Op::Shutdown).await;
+        if let Some(id) = &self.session_id {
+            let resume_cmd = format!("cleon --resume {id}");
+            let info = json!({
+                "type": "session.resume",
+                "session_id": id,
+                "rollout_path": self.rollout_path,
+                "resume_command": resume_cmd,
+            });
+            println!("{info}");
+            self.forward_webhook("session.resume", info.clone()).await;
+            self.forward_gateway(&info);
+        }
+        Ok(())
+    }
+}
+
+/// One resolved (or never-resolved) approval request from a turn, recording
+/// not just what was decided but how: a human answering it, a
+/// `--approval-timeout` auto-deny, or the turn being interrupted before
+/// anyone answered.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ApprovalOutcome {
+    pub id: String,
+    pub kind: ApprovalKind,
+    pub decision: ReviewDecision,
+    pub resolution: ApprovalResolution,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ApprovalResolution {
+    User,
+    Timeout,
+    Interrupted,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct TurnResult {
+    events: Vec<ThreadEvent>,
+    pub final_message: Option<String>,
+    pub reasoning: Vec<String>,
+    pub usage: Option<Usage>,
+    pub errors: Vec<String>,
+    pub approvals: Vec<ApprovalOutcome>,
+    /// `final_message` parsed as JSON and validated against
+    /// `--output-schema`/`/schema`, if one is set and validation succeeded.
+    pub structured_output: Option<serde_json::Value>,
+    #[serde(skip)]
+    completed: bool,
+    /// Set when `--output-schema`/`/schema` is configured and
+    /// `final_message` was actually checked against it and failed (bad
+    /// JSON, schema mismatch, or an uncompilable schema). Left `false` when
+    /// there was simply no `final_message` to check, so callers can
+    /// distinguish a real validation failure (recorded in `errors`) from an
+    /// empty turn.
+    #[serde(skip)]
+    schema_validation_failed: bool,
+}
+
+impl TurnResult {
+    /// The raw `ThreadEvent`s captured over the course of the turn.
+    pub fn events(&self) -> &[ThreadEvent] {
+        &self.events
+    }
+
+    /// Whether `--output-schema`/`/schema` was configured and
+    /// `final_message` actually failed that check (as opposed to there
+    /// being no `final_message` to check in the first place).
+    pub fn schema_validation_failed(&self) -> bool {
+        self.schema_validation_failed
+    }
+
+    fn append_events(&mut self, events: Vec<ThreadEvent>) {
+        for event in events {
+            self.update_from_event(&event);
+            self.events.push(event);
+        }
+    }
+
+    /// Record approvals that were still outstanding when the turn was
+    /// interrupted; nothing will ever answer them now.
+    fn record_interrupted_approvals(&mut self, abandoned: Vec<(String, ApprovalKind)>) {
+        for (id, kind) in abandoned {
+            self.approvals.push(ApprovalOutcome {
+                id,
+                kind,
+                decision: ReviewDecision::Abort,
+                resolution: ApprovalResolution::Interrupted,
+            });
+        }
+    }
+
+    fn update_from_event(&mut self, event: &ThreadEvent) {
+        match event {
+            ThreadEvent::TurnCompleted(ev) => {
+                self.completed = true;
+                self.usage = Some(ev.usage.clone());
+            }
+            ThreadEvent::TurnFailed(ev) => {
+                self.completed = true;
+                self.errors.push(ev.error.message.clone());
+            }
+            ThreadEvent::Error(err) => {
+                self.errors.push(err.message.clone());
+            }
+            ThreadEvent::ItemCompleted(item) => self.capture_item(&item.item),
+            ThreadEvent::ItemUpdated(item) => self.capture_item(&item.item),
+            _ => {}
+        }
+    }
+
+    fn capture_item(&mut self, item: &codex_exec::exec_events::ThreadItem) {
+        match &item.details {
+            ThreadItemDetails::AgentMessage(msg) => {
+                self.final_message = Some(msg.text.clone());
+            }
+            ThreadItemDetails::Reasoning(reason) => {
+                self.reasoning.push(reason.text.clone());
+            }
+            _ => {}
+        }
+    }
+
+    fn turn_complete(&self) -> bool {
+        self.completed
+    }
+}
+
+/// Poll a gateway's inbound channel, or never resolve if there is none —
+/// lets `collect_turn_events_inner` include this as an optional
+/// `tokio::select!` branch without special-casing the no-gateway case in the
+/// macro itself.
+async fn recv_gateway(gateway: Option<&mut GatewayHandle>) -> Option<ClientRequest> {
+    match gateway {
+        Some(gateway) => gateway.inbound.recv().await,
+        None => std::future::pending().await,
+    }
+}
+
+pub fn parse_decision(input: &str) -> Option<ReviewDecision> {
+    match input.trim().to_lowercase().as_str() {
+        "approve" | "y" | "yes" => Some(ReviewDecision::Approved),
+        "approve_session" | "session" | "always" => Some(ReviewDecision::ApprovedForSession),
+        "deny" | "n" | "no" => Some(ReviewDecision::Denied),
+        "abort" | "stop" => Some(ReviewDecision::Abort),
+        _ => None,
+    }
+}
+
+pub async fn load_config() -> Result<Config> {
+    let overrides = ConfigOverrides::default();
+    Config::load_with_cli_overrides(Vec::new(), overrides)
+        .await
+        .context("failed to load Codex config")
+}
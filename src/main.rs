@@ -1,30 +1,22 @@
 use std::io::{self, IsTerminal, Read, Write};
-use std::path::PathBuf;
-use std::sync::Arc;
 
 use anyhow::{Context, Result, bail};
 use clap::{Args, Parser, Subcommand};
+use cleon::{
+    CodexSession, ListenAddr, TurnResult, WebhookSink, gateway, load_config, load_output_schema,
+    parse_decision,
+};
 use codex_app_server_protocol::AuthMode;
-use codex_core::auth::{self, enforce_login_restrictions, login_with_api_key, logout};
-use codex_core::config::{Config, ConfigOverrides};
+use codex_core::CodexAuth;
+use codex_core::auth::{self, login_with_api_key, logout};
 use codex_core::default_client::{self, SetOriginatorError};
-use codex_core::find_conversation_path_by_id_str;
-use codex_core::protocol::{
-    AskForApproval, Event, EventMsg, Op, ReviewDecision, SandboxPolicy, SessionSource,
-};
-use codex_core::{AuthManager, CodexAuth, ConversationManager, NewConversation};
-use codex_exec::event_processor_with_jsonl_output::EventProcessorWithJsonOutput;
-use codex_exec::exec_events::{ThreadEvent, ThreadItemDetails, Usage};
+use codex_core::protocol::ReviewDecision;
 use codex_login::{ServerOptions, run_device_code_login, run_login_server};
-use codex_protocol::config_types::{
-    ForcedLoginMethod, ReasoningEffort as ReasoningEffortConfig, ReasoningSummary,
-};
-use codex_protocol::user_input::UserInput;
-use serde::Serialize;
-use serde_json::json;
-use tokio::io::{AsyncBufReadExt, BufReader};
-use tokio::signal;
-use tokio::sync::mpsc::{UnboundedReceiver, unbounded_channel};
+use codex_protocol::config_types::ForcedLoginMethod;
+
+mod commands;
+
+use commands::SessionCommand;
 
 #[derive(Debug, Parser)]
 #[command(
@@ -53,6 +45,34 @@ struct Cli {
     #[arg(long = "json-result", default_value_t = false)]
     json_result: bool,
 
+    /// Accept control connections instead of reading from stdin, e.g.
+    /// `unix:/tmp/cleon.sock` or `ws://127.0.0.1:4455`.
+    #[arg(long = "listen", value_name = "ADDR")]
+    listen: Option<ListenAddr>,
+
+    /// POST every event produced by this session to this URL, one JSON
+    /// request per event.
+    #[arg(long = "webhook", value_name = "URL")]
+    webhook: Option<String>,
+
+    /// Auto-resolve an outstanding exec/patch approval after this many
+    /// seconds instead of waiting forever.
+    #[arg(long = "approval-timeout", value_name = "SECS")]
+    approval_timeout: Option<u64>,
+
+    /// Decision to auto-submit when `--approval-timeout` elapses.
+    #[arg(
+        long = "approval-timeout-decision",
+        value_name = "DECISION",
+        default_value = "deny"
+    )]
+    approval_timeout_decision: String,
+
+    /// Require the final turn message to validate against this JSON Schema
+    /// document (a file path, or `-` to read it from stdin).
+    #[arg(long = "output-schema", value_name = "PATH|-")]
+    output_schema: Option<String>,
+
     #[command(subcommand)]
     command: Option<Command>,
 }
@@ -104,6 +124,11 @@ async fn run() -> Result<()> {
         non_interactive,
         json_events,
         json_result,
+        listen,
+        webhook,
+        approval_timeout,
+        approval_timeout_decision,
+        output_schema,
         command,
     } = Cli::parse();
 
@@ -111,33 +136,100 @@ async fn run() -> Result<()> {
         Some(Command::Login(args)) => handle_login(args).await,
         Some(Command::Logout) => handle_logout().await,
         Some(Command::Status) => handle_status().await,
-        None => run_session(prompt, resume, !non_interactive, json_events, json_result).await,
+        None => {
+            let approval_timeout_decision = parse_decision(&approval_timeout_decision)
+                .with_context(|| {
+                    format!("invalid --approval-timeout-decision {approval_timeout_decision:?}")
+                })?;
+            let output_schema = output_schema
+                .map(|source| load_output_schema(&source))
+                .transpose()?;
+            run_session(
+                prompt,
+                resume,
+                !non_interactive,
+                json_events,
+                json_result,
+                listen,
+                webhook,
+                approval_timeout.map(std::time::Duration::from_secs),
+                approval_timeout_decision,
+                output_schema,
+            )
+            .await
+        }
     }
 }
 
+#[allow(clippy::too_many_arguments)]
 async fn run_session(
     prompt: Option<String>,
     resume_session: Option<String>,
     interactive: bool,
     json_events: bool,
     json_result: bool,
+    listen: Option<ListenAddr>,
+    webhook: Option<String>,
+    approval_timeout: Option<std::time::Duration>,
+    approval_timeout_decision: ReviewDecision,
+    output_schema: Option<cleon::OutputSchema>,
 ) -> Result<()> {
-    let mut session = FullCodexSession::new(resume_session).await?;
+    let mut builder = CodexSession::builder().approval_timeout_decision(approval_timeout_decision);
+    if let Some(resume_session) = resume_session {
+        builder = builder.resume(resume_session);
+    }
+    if let Some(approval_timeout) = approval_timeout {
+        builder = builder.approval_timeout(approval_timeout);
+    }
+    if let Some(output_schema) = output_schema {
+        builder = builder.output_schema(output_schema);
+    }
+    let mut session = builder.build().await?;
 
-    if interactive {
+    if let Some(url) = webhook {
+        let session_id = session.session_id().unwrap_or_default().to_string();
+        session.attach_webhook(WebhookSink::spawn(url, session_id)?);
+    }
+
+    let mut schema_validation_failed = false;
+    if let Some(listen) = listen {
+        session.attach_gateway(gateway::spawn(listen).await?);
+        run_gateway_driven(&mut session, json_events, json_result).await?;
+    } else if interactive {
         run_interactive(&mut session, prompt, json_events, json_result).await?;
     } else {
         let prompt = read_prompt(prompt)?;
         let result = session.send_turn(prompt, json_events).await?;
         output_turn_result(&result, json_result)?;
+        schema_validation_failed = result.schema_validation_failed();
     }
 
     session.shutdown().await?;
+    if schema_validation_failed {
+        bail!("final_message failed --output-schema validation; see turn.result.errors");
+    }
+    Ok(())
+}
+
+/// Drive turns entirely from gateway clients: block for the next `sendTurn`
+/// request and run it to completion. Every `ThreadEvent`, `approval.request`,
+/// and `turn.result` envelope produced along the way is already broadcast to
+/// connected clients from inside `send_turn`/`collect_turn_events`; this loop
+/// only needs to mirror the result to our own stdout.
+async fn run_gateway_driven(
+    session: &mut CodexSession,
+    json_events: bool,
+    json_result: bool,
+) -> Result<()> {
+    while let Some(text) = session.next_gateway_turn().await {
+        let result = session.send_turn(text, json_events).await?;
+        output_turn_result(&result, json_result)?;
+    }
     Ok(())
 }
 
 async fn run_interactive(
-    session: &mut FullCodexSession,
+    session: &mut CodexSession,
     initial_prompt: Option<String>,
     json_events: bool,
     json_result: bool,
@@ -162,12 +254,43 @@ async fn run_interactive(
         if trimmed.is_empty() {
             continue;
         }
+        match commands::parse(trimmed) {
+            Ok(Some(command)) => {
+                if let Err(err) = apply_session_command(session, command) {
+                    eprintln!("error: {err:?}");
+                }
+                continue;
+            }
+            Ok(None) => {}
+            Err(err) => {
+                eprintln!("error: {err:?}");
+                continue;
+            }
+        }
         let result = session.send_turn(trimmed.to_string(), json_events).await?;
         output_turn_result(&result, json_result)?;
     }
     Ok(())
 }
 
+/// Apply a parsed `/`-command to the session's defaults. `/show` is handled
+/// here too since printing is otherwise just as cheap as mutating a field.
+fn apply_session_command(session: &mut CodexSession, command: SessionCommand) -> Result<()> {
+    match command {
+        SessionCommand::Model(model) => session.set_model(model),
+        SessionCommand::Effort(effort) => session.set_effort(effort),
+        SessionCommand::Approval(approval) => session.set_approval(approval),
+        SessionCommand::Sandbox(policy) => session.set_sandbox_policy(policy),
+        SessionCommand::Cwd(cwd) => session.set_cwd(cwd),
+        SessionCommand::Schema(path) => {
+            let source = path.display().to_string();
+            session.set_output_schema(Some(load_output_schema(&source)?));
+        }
+        SessionCommand::Show => println!("{}", session.settings_json()),
+    }
+    Ok(())
+}
+
 async fn handle_login(args: LoginArgs) -> Result<()> {
     if args.device_code && (args.with_api_key || args.api_key.is_some()) {
         bail!("--device-code cannot be combined with API key options");
@@ -286,313 +409,6 @@ fn output_turn_result(result: &TurnResult, _json_result: bool) -> Result<()> {
     Ok(())
 }
 
-struct FullCodexSession {
-    conversation: Arc<codex_core::CodexConversation>,
-    event_rx: UnboundedReceiver<Event>,
-    event_processor: EventProcessorWithJsonOutput,
-    bootstrap_events: Vec<ThreadEvent>,
-    session_id: Option<String>,
-    rollout_path: Option<String>,
-    default_cwd: PathBuf,
-    default_approval: AskForApproval,
-    default_sandbox_policy: SandboxPolicy,
-    default_model: String,
-    default_effort: Option<ReasoningEffortConfig>,
-    default_summary: ReasoningSummary,
-}
-
-impl FullCodexSession {
-    async fn new(resume_session: Option<String>) -> Result<Self> {
-        let config = Arc::new(load_config().await?);
-
-        enforce_login_restrictions(&config)
-            .await
-            .context("login restrictions check failed")?;
-
-        let auth_manager = AuthManager::shared(
-            config.codex_home.clone(),
-            true,
-            config.cli_auth_credentials_store_mode,
-        );
-
-        let conversation_manager =
-            ConversationManager::new(auth_manager.clone(), SessionSource::Cli);
-        let NewConversation {
-            conversation_id: _,
-            conversation,
-            session_configured,
-        } = if let Some(resume) = resume_session {
-            let path = find_conversation_path_by_id_str(&config.codex_home, &resume)
-                .await
-                .context("failed to search for session to resume")?;
-            let Some(rollout_path) = path else {
-                bail!("No saved session found with ID {resume}");
-            };
-            conversation_manager
-                .resume_conversation_from_rollout(
-                    (*config).clone(),
-                    rollout_path,
-                    auth_manager.clone(),
-                )
-                .await?
-        } else {
-            conversation_manager
-                .new_conversation((*config).clone())
-                .await?
-        };
-
-        let (tx, rx) = unbounded_channel::<Event>();
-        let event_conversation = conversation.clone();
-        tokio::spawn(async move {
-            loop {
-                match event_conversation.next_event().await {
-                    Ok(event) => {
-                        if tx.send(event).is_err() {
-                            break;
-                        }
-                    }
-                    Err(err) => {
-                        eprintln!("event stream closed: {err}");
-                        break;
-                    }
-                }
-            }
-        });
-
-        let mut event_processor = EventProcessorWithJsonOutput::new(None);
-        let bootstrap_event = Event {
-            id: String::new(),
-            msg: EventMsg::SessionConfigured(session_configured.clone()),
-        };
-        let bootstrap_events = event_processor.collect_thread_events(&bootstrap_event);
-        let mut session_id = None;
-        let mut rollout_path = None;
-        if let EventMsg::SessionConfigured(cfg) = &bootstrap_event.msg {
-            session_id = Some(cfg.session_id.to_string());
-            rollout_path = Some(cfg.rollout_path.display().to_string());
-        }
-
-        Ok(Self {
-            conversation,
-            event_rx: rx,
-            event_processor,
-            bootstrap_events,
-            session_id,
-            rollout_path,
-            default_cwd: config.cwd.clone(),
-            default_approval: config.approval_policy,
-            default_sandbox_policy: config.sandbox_policy.clone(),
-            default_model: config.model.clone(),
-            default_effort: config.model_reasoning_effort,
-            default_summary: config.model_reasoning_summary,
-        })
-    }
-
-    async fn send_turn(&mut self, user_text: String, emit_json_events: bool) -> Result<TurnResult> {
-        let items = vec![UserInput::Text { text: user_text }];
-
-        self.conversation
-            .submit(Op::UserTurn {
-                items,
-                cwd: self.default_cwd.clone(),
-                approval_policy: self.default_approval,
-                sandbox_policy: self.default_sandbox_policy.clone(),
-                model: self.default_model.clone(),
-                effort: self.default_effort,
-                summary: self.default_summary,
-                final_output_json_schema: None,
-            })
-            .await?;
-
-        self.collect_turn_events(emit_json_events).await
-    }
-
-    async fn collect_turn_events(&mut self, emit_json_events: bool) -> Result<TurnResult> {
-        let mut result = TurnResult::default();
-        let mut approvals: VecDeque<(String, EventMsg)> = VecDeque::new();
-        let mut stdin_lines = BufReader::new(tokio::io::stdin()).lines();
-
-        if !self.bootstrap_events.is_empty() {
-            if emit_json_events {
-                for event in &self.bootstrap_events {
-                    println!("{}", serde_json::to_string(event)?);
-                }
-            }
-            result.append_events(std::mem::take(&mut self.bootstrap_events));
-        }
-
-        loop {
-            tokio::select! {
-                _ = signal::ctrl_c() => {
-                    let _ = self.conversation.submit(Op::Interrupt).await;
-                    result.errors.push("Interrupted by user".to_string());
-                    result.completed = true;
-                    break;
-                }
-                maybe_event = self.event_rx.recv() => {
-                    let Some(event) = maybe_event else {
-                        break;
-                    };
-                    match &event.msg {
-                EventMsg::SessionConfigured(cfg) => {
-                    self.session_id = Some(cfg.session_id.to_string());
-                    self.rollout_path = Some(cfg.rollout_path.display().to_string());
-                }
-                EventMsg::ExecApprovalRequest(req) => {
-                    approvals.push_back((event.id.clone(), EventMsg::ExecApprovalRequest(req.clone())));
-                    println!("{}", serde_json::to_string(&serde_json::json!({
-                        "type": "approval.request",
-                        "id": event.id,
-                        "kind": "exec",
-                        "command": req.command,
-                        "cwd": req.cwd,
-                        "reason": req.reason,
-                        "risk": req.risk,
-                    }))?);
-                    eprintln!(
-                        "APPROVAL REQUEST {}: command={:?} cwd={} reason={:?} risk={:?}",
-                        event.id, req.command, req.cwd.display(), req.reason, req.risk.as_ref().map(|r| r.risk_level.as_str())
-                    );
-                    eprintln!("Respond with: approve | approve_session | deny | abort");
-                }
-                EventMsg::ApplyPatchApprovalRequest(req) => {
-                    approvals.push_back((event.id.clone(), EventMsg::ApplyPatchApprovalRequest(req.clone())));
-                    println!("{}", serde_json::to_string(&serde_json::json!({
-                        "type": "approval.request",
-                        "id": event.id,
-                        "kind": "patch",
-                        "reason": req.reason,
-                        "grant_root": req.grant_root,
-                        "files": req.changes.keys().collect::<Vec<_>>(),
-                    }))?);
-                    eprintln!(
-                        "PATCH APPROVAL {}: files={} reason={:?} grant_root={:?}",
-                        event.id, req.changes.len(), req.reason, req.grant_root
-                    );
-                    eprintln!("Respond with: approve | approve_session | deny | abort");
-                        }
-                        _ => {}
-                    }
-                    let thread_events = self.event_processor.collect_thread_events(&event);
-                    if emit_json_events {
-                        for ev in &thread_events {
-                            println!("{}", serde_json::to_string(ev)?);
-                        }
-                    }
-                    result.append_events(thread_events);
-                    if result.turn_complete() {
-                        break;
-                    }
-                }
-                line = stdin_lines.next_line(), if !approvals.is_empty() => {
-                    let Some(line) = line? else { continue };
-                    if let Some((id, pending)) = approvals.pop_front() {
-                        match parse_decision(&line) {
-                            Some(decision) => {
-                                match pending {
-                                    EventMsg::ExecApprovalRequest(_) => {
-                                        self.conversation.submit(Op::ExecApproval { id, decision }).await?;
-                                    }
-                                    EventMsg::ApplyPatchApprovalRequest(_) => {
-                                        self.conversation.submit(Op::PatchApproval { id, decision }).await?;
-                                    }
-                                    _ => {}
-                                }
-                            }
-                            None => {
-                                eprintln!("invalid approval response, expected one of: approve, approve_session, deny, abort");
-                                approvals.push_front((id, pending));
-                            }
-                        }
-                    }
-                }
-            }
-        }
-
-        Ok(result)
-    }
-
-    async fn shutdown(&self) -> Result<()> {
-        let _ = self.conversation.submit(Op::Shutdown).await;
-        if let Some(id) = &self.session_id {
-            let resume_cmd = format!("cleon --resume {id}");
-            let info = json!({
-                "type": "session.resume",
-                "session_id": id,
-                "rollout_path": self.rollout_path,
-                "resume_command": resume_cmd,
-            });
-            println!("{info}");
-        }
-        Ok(())
-    }
-}
-
-#[derive(Debug, Default, Serialize)]
-pub struct TurnResult {
-    events: Vec<ThreadEvent>,
-    pub final_message: Option<String>,
-    pub reasoning: Vec<String>,
-    pub usage: Option<Usage>,
-    pub errors: Vec<String>,
-    #[serde(skip_serializing)]
-    completed: bool,
-}
-
-impl TurnResult {
-    fn append_events(&mut self, events: Vec<ThreadEvent>) {
-        for event in events {
-            self.update_from_event(&event);
-            self.events.push(event);
-        }
-    }
-
-    fn update_from_event(&mut self, event: &ThreadEvent) {
-        match event {
-            ThreadEvent::TurnCompleted(ev) => {
-                self.completed = true;
-                self.usage = Some(ev.usage.clone());
-            }
-            ThreadEvent::TurnFailed(ev) => {
-                self.completed = true;
-                self.errors.push(ev.error.message.clone());
-            }
-            ThreadEvent::Error(err) => {
-                self.errors.push(err.message.clone());
-            }
-            ThreadEvent::ItemCompleted(item) => self.capture_item(&item.item),
-            ThreadEvent::ItemUpdated(item) => self.capture_item(&item.item),
-            _ => {}
-        }
-    }
-
-    fn capture_item(&mut self, item: &codex_exec::exec_events::ThreadItem) {
-        match &item.details {
-            ThreadItemDetails::AgentMessage(msg) => {
-                self.final_message = Some(msg.text.clone());
-            }
-            ThreadItemDetails::Reasoning(reason) => {
-                self.reasoning.push(reason.text.clone());
-            }
-            _ => {}
-        }
-    }
-
-    fn turn_complete(&self) -> bool {
-        self.completed
-    }
-}
-
-fn parse_decision(input: &str) -> Option<ReviewDecision> {
-    match input.trim().to_lowercase().as_str() {
-        "approve" | "y" | "yes" => Some(ReviewDecision::Approved),
-        "approve_session" | "session" | "always" => Some(ReviewDecision::ApprovedForSession),
-        "deny" | "n" | "no" => Some(ReviewDecision::Denied),
-        "abort" | "stop" => Some(ReviewDecision::Abort),
-        _ => None,
-    }
-}
-
 fn read_prompt(prompt: Option<String>) -> Result<String> {
     match prompt {
         Some(p) if p.trim() == "-" => read_prompt_from_stdin(),
@@ -641,11 +457,3 @@ fn safe_key_preview(key: &str) -> String {
     let suffix = &key[key.len() - 3..];
     format!("{prefix}***{suffix}")
 }
-
-async fn load_config() -> Result<Config> {
-    let overrides = ConfigOverrides::default();
-    Config::load_with_cli_overrides(Vec::new(), overrides)
-        .await
-        .context("failed to load Codex config")
-}
-use std::collections::VecDeque;
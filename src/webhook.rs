@@ -0,0 +1,91 @@
+//! HTTP webhook sink: forwards every event produced by a session to a URL as
+//! one JSON POST per event, in order, so automation can consume a session
+//! without parsing our stdout.
+//!
+//! Delivery runs on a background task behind a bounded channel. A merely
+//! slow endpoint backs the channel up, which applies real backpressure:
+//! [`WebhookSink::send`] awaits room in the queue rather than blocking on
+//! the HTTP call itself. Only once the queue has stayed full for longer
+//! than [`BACKPRESSURE_TIMEOUT`] — the endpoint is stuck, not just slow —
+//! do we give up, drop the event, and log a warning; the `seq` field lets
+//! the receiver notice the gap.
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use anyhow::{Context, Result};
+use serde_json::json;
+use tokio::sync::mpsc::{self, Sender};
+
+const QUEUE_CAPACITY: usize = 256;
+
+/// How long [`WebhookSink::send`] will wait for room in a full queue before
+/// giving up and dropping the event. Bounds how much backpressure a stuck
+/// endpoint can apply to the session's event loop.
+const BACKPRESSURE_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(5);
+
+pub struct WebhookSink {
+    tx: Sender<serde_json::Value>,
+    session_id: String,
+    sequence: AtomicU64,
+}
+
+impl WebhookSink {
+    pub fn spawn(url: String, session_id: String) -> Result<Arc<Self>> {
+        let client = reqwest::Client::builder()
+            .build()
+            .context("failed to build webhook HTTP client")?;
+        let (tx, mut rx) = mpsc::channel::<serde_json::Value>(QUEUE_CAPACITY);
+
+        tokio::spawn(async move {
+            while let Some(payload) = rx.recv().await {
+                if let Err(err) = client.post(&url).json(&payload).send().await {
+                    eprintln!("webhook: delivery to {url} failed: {err}");
+                }
+            }
+        });
+
+        Ok(Arc::new(Self {
+            tx,
+            session_id,
+            sequence: AtomicU64::new(0),
+        }))
+    }
+
+    /// Enqueue one event for delivery. `event_type` is `thread_event` for a
+    /// raw `ThreadEvent`, or else the `type` used in our stdout envelopes
+    /// (`approval.request`, `turn.result`, `session.resume`).
+    ///
+    /// If the queue is already full this awaits room rather than dropping
+    /// immediately, so a slow endpoint applies backpressure to the caller
+    /// instead of silently losing events. Only a queue that stays full for
+    /// longer than [`BACKPRESSURE_TIMEOUT`] results in a dropped event.
+    pub async fn send(&self, event_type: &str, body: serde_json::Value) {
+        let seq = self.sequence.fetch_add(1, Ordering::Relaxed);
+        let payload = json!({
+            "session_id": self.session_id,
+            "seq": seq,
+            "type": event_type,
+            "event": body,
+        });
+        let payload = match self.tx.try_send(payload) {
+            Ok(()) => return,
+            Err(mpsc::error::TrySendError::Full(payload)) => payload,
+            Err(mpsc::error::TrySendError::Closed(_)) => {
+                eprintln!("webhook: delivery task gone, dropping event seq={seq} type={event_type}");
+                return;
+            }
+        };
+        match tokio::time::timeout(BACKPRESSURE_TIMEOUT, self.tx.send(payload)).await {
+            Ok(Ok(())) => {}
+            Ok(Err(_)) => {
+                eprintln!("webhook: delivery task gone, dropping event seq={seq} type={event_type}");
+            }
+            Err(_) => {
+                eprintln!(
+                    "webhook: queue still full after {BACKPRESSURE_TIMEOUT:?}, dropping event seq={seq} type={event_type}"
+                );
+            }
+        }
+    }
+}